@@ -0,0 +1,248 @@
+//! Async dispatch support: a small worker pool that runs request handlers off
+//! the calling thread, with cooperative cancellation via `JobToken`.
+
+use std::any::Any;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::Request;
+
+/// Uniquely identifies an in-flight asynchronous request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RequestId(u64);
+
+static NEXT_REQUEST_ID: AtomicU64 = AtomicU64::new(0);
+
+impl RequestId {
+    fn next() -> Self {
+        Self(NEXT_REQUEST_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// Shared cancellation flag for a single in-flight job.
+type CancelFlag = Arc<AtomicBool>;
+
+/// Handed to a cancellable handler so it can poll for cancellation mid-flight.
+#[derive(Clone)]
+pub struct JobToken {
+    flag: CancelFlag,
+}
+
+impl JobToken {
+    fn new(flag: CancelFlag) -> Self {
+        Self { flag }
+    }
+
+    /// Returns true once `.cancel()` has been called on the matching `JobHandle`.
+    pub fn is_cancelled(&self) -> bool {
+        self.flag.load(Ordering::Relaxed)
+    }
+}
+
+/// Processes a request asynchronously, polling `token` to support cancellation.
+pub trait CancellableRequestHandler<R: Request, Resp>: Send + Sync + 'static {
+    fn handle(&self, request: R, token: JobToken) -> Resp;
+}
+
+/// A handle to a request submitted to the worker pool.
+///
+/// Dropping the handle without calling `.join()` simply abandons the result;
+/// the worker still runs the handler to completion (or cancellation).
+pub struct JobHandle<Resp> {
+    id: RequestId,
+    receiver: Receiver<Resp>,
+    cancel_flag: CancelFlag,
+}
+
+impl<Resp> JobHandle<Resp> {
+    /// The id assigned to this in-flight call.
+    pub fn id(&self) -> RequestId {
+        self.id
+    }
+
+    /// Block the calling thread until the handler completes and return its response.
+    ///
+    /// Returns `None` if no handler was registered, or the handler panicked.
+    pub fn join(self) -> Option<Resp> {
+        self.receiver.recv().ok()
+    }
+
+    /// Request that the handler stop as soon as it next polls its `JobToken`.
+    pub fn cancel(&self) {
+        self.cancel_flag.store(true, Ordering::Relaxed);
+    }
+}
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Minimal fixed-size thread pool used to run handlers off the caller's thread.
+pub(crate) struct WorkerPool {
+    sender: Sender<Job>,
+}
+
+impl WorkerPool {
+    pub(crate) fn new(size: usize) -> Self {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        for _ in 0..size.max(1) {
+            let receiver = Arc::clone(&receiver);
+            thread::spawn(move || loop {
+                let job = receiver.lock().unwrap().recv();
+                match job {
+                    Ok(job) => job(),
+                    Err(_) => break,
+                }
+            });
+        }
+
+        Self { sender }
+    }
+
+    pub(crate) fn submit(&self, job: Job) {
+        // Every `Mediator` outlives its pool threads, so this only fails if
+        // every worker thread has panicked away; silently dropping the job in that
+        // case is no worse than the panic that caused it.
+        let _ = self.sender.send(job);
+    }
+}
+
+/// Boxes a cancellable handler the same way `Mediator::register_request` boxes
+/// a synchronous one: once as the trait object, once more as `Any` so it can
+/// live in the `Mediator`'s `TypeId`-keyed map of async request handlers.
+pub(crate) fn boxed_async_handler<R, Resp, H>(handler: H) -> Box<dyn Any + Send + Sync>
+where
+    R: Request,
+    Resp: 'static,
+    H: CancellableRequestHandler<R, Resp>,
+{
+    let handler: Arc<dyn CancellableRequestHandler<R, Resp>> = Arc::new(handler);
+    Box::new(handler)
+}
+
+pub(crate) fn submit_async_request<R, Resp>(
+    pool: &WorkerPool,
+    boxed_handler: Option<&Box<dyn Any + Send + Sync>>,
+    request: R,
+) -> JobHandle<Resp>
+where
+    R: Request + Send,
+    Resp: Send + 'static,
+{
+    let id = RequestId::next();
+    let cancel_flag: CancelFlag = Arc::new(AtomicBool::new(false));
+    let token = JobToken::new(Arc::clone(&cancel_flag));
+
+    let handler = boxed_handler
+        .and_then(|h| h.downcast_ref::<Arc<dyn CancellableRequestHandler<R, Resp>>>())
+        .cloned();
+
+    let (sender, receiver) = mpsc::channel();
+
+    pool.submit(Box::new(move || {
+        // Catch a handler panic here the same way `Responder` does for the
+        // synchronous path: without it, the panic unwinds straight through
+        // the pool's dispatch loop and takes the worker thread down with it,
+        // silently wedging every future job submitted to this pool.
+        let outcome = handler.map(|handler| {
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                handler.handle(request, token)
+            }))
+        });
+
+        if let Some(Ok(response)) = outcome {
+            let _ = sender.send(response);
+        }
+    }));
+
+    JobHandle {
+        id,
+        receiver,
+        cancel_flag,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    struct Echo(u32);
+
+    impl Request for Echo {}
+
+    struct EchoHandler;
+
+    impl CancellableRequestHandler<Echo, u32> for EchoHandler {
+        fn handle(&self, request: Echo, _token: JobToken) -> u32 {
+            request.0
+        }
+    }
+
+    struct SpinUntilCancelled;
+
+    impl CancellableRequestHandler<Echo, u32> for SpinUntilCancelled {
+        fn handle(&self, request: Echo, token: JobToken) -> u32 {
+            while !token.is_cancelled() {
+                thread::sleep(Duration::from_millis(1));
+            }
+            request.0
+        }
+    }
+
+    struct PanickingHandler;
+
+    impl CancellableRequestHandler<Echo, u32> for PanickingHandler {
+        fn handle(&self, _request: Echo, _token: JobToken) -> u32 {
+            panic!("boom");
+        }
+    }
+
+    #[test]
+    fn submit_async_request_runs_on_the_pool() {
+        let pool = WorkerPool::new(1);
+        let boxed: Box<dyn Any + Send + Sync> = boxed_async_handler::<Echo, u32, _>(EchoHandler);
+
+        let handle = submit_async_request::<Echo, u32>(&pool, Some(&boxed), Echo(42));
+
+        assert_eq!(handle.join(), Some(42));
+    }
+
+    #[test]
+    fn submit_async_request_without_handler_joins_to_none() {
+        let pool = WorkerPool::new(1);
+
+        let handle = submit_async_request::<Echo, u32>(&pool, None, Echo(1));
+
+        assert_eq!(handle.join(), None);
+    }
+
+    #[test]
+    fn cancel_lets_a_polling_handler_observe_it() {
+        let pool = WorkerPool::new(1);
+        let boxed: Box<dyn Any + Send + Sync> =
+            boxed_async_handler::<Echo, u32, _>(SpinUntilCancelled);
+
+        let handle = submit_async_request::<Echo, u32>(&pool, Some(&boxed), Echo(7));
+
+        handle.cancel();
+        assert_eq!(handle.join(), Some(7));
+    }
+
+    #[test]
+    fn a_panicking_handler_joins_to_none_and_does_not_wedge_the_pool() {
+        let pool = WorkerPool::new(1);
+        let boxed: Box<dyn Any + Send + Sync> =
+            boxed_async_handler::<Echo, u32, _>(PanickingHandler);
+
+        let handle = submit_async_request::<Echo, u32>(&pool, Some(&boxed), Echo(1));
+        assert_eq!(handle.join(), None);
+
+        // The pool's sole worker thread must have survived the panic to run this.
+        let boxed: Box<dyn Any + Send + Sync> = boxed_async_handler::<Echo, u32, _>(EchoHandler);
+        let handle = submit_async_request::<Echo, u32>(&pool, Some(&boxed), Echo(9));
+        assert_eq!(handle.join(), Some(9));
+    }
+}
@@ -0,0 +1,55 @@
+//! Structured dispatch errors, and a `Responder` that funnels a handler
+//! invocation's outcome - including panics - into a single typed `Result`.
+
+use std::fmt;
+
+use crate::{Request, RequestHandler};
+
+/// Errors that can occur while dispatching a request through a `Mediator`.
+#[derive(Debug)]
+pub enum MediatorError {
+    /// No handler was registered for the request's type.
+    NoHandler { type_name: &'static str },
+    /// A handler was registered for the request, but for a different response type.
+    TypeMismatch {
+        expected: &'static str,
+        found: &'static str,
+    },
+    /// The handler panicked while processing the request.
+    HandlerPanicked,
+}
+
+impl fmt::Display for MediatorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MediatorError::NoHandler { type_name } => {
+                write!(f, "no handler registered for request `{type_name}`")
+            }
+            MediatorError::TypeMismatch { expected, found } => write!(
+                f,
+                "handler found, but response type mismatch: expected `{expected}`, found `{found}`"
+            ),
+            MediatorError::HandlerPanicked => {
+                write!(f, "handler panicked while processing request")
+            }
+        }
+    }
+}
+
+impl std::error::Error for MediatorError {}
+
+/// Funnels a handler invocation through one place: the success value passes
+/// through untouched, and a panic inside the handler is caught and mapped to
+/// `MediatorError::HandlerPanicked` instead of unwinding across the mediator.
+pub(crate) struct Responder;
+
+impl Responder {
+    pub(crate) fn respond<R, Resp, H>(handler: &H, request: R) -> Result<Resp, MediatorError>
+    where
+        R: Request,
+        H: RequestHandler<R, Resp> + ?Sized,
+    {
+        std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| handler.handle(request)))
+            .map_err(|_| MediatorError::HandlerPanicked)
+    }
+}
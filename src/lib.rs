@@ -1,4 +1,18 @@
-use std::{any::{Any, TypeId}, collections::HashMap};
+use std::{
+    any::{type_name, Any, TypeId},
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+mod async_dispatch;
+mod error;
+mod notifications;
+mod storage;
+
+pub use async_dispatch::{CancellableRequestHandler, JobHandle, JobToken, RequestId};
+pub use error::MediatorError;
+pub use notifications::{DeliveryMode, NotificationEntry};
+pub use storage::{EventId, HandlerStorage, HashMapStorage, VecStorage};
 
 /// Marker trait for all requests that go through the mediator
 pub trait Request: 'static {}
@@ -16,19 +30,54 @@ pub trait NotificationHandler<N: Notification>: 'static {
     fn handle(&self, notification: N);
 }
 
-/// Core mediator struct, owns and dispatches handlers
-pub struct Mediator {
+/// Default number of worker threads backing `Mediator::new`'s async dispatch pool.
+const DEFAULT_POOL_SIZE: usize = 4;
+
+/// A registered request handler, along with the response type it was
+/// registered against so a later type mismatch can name both sides.
+struct RequestSlot {
+    response_type_name: &'static str,
+    handler: Box<dyn Any>,
+}
+
+/// Core mediator struct, owns and dispatches handlers.
+///
+/// Generic over `S`, the backing store for notification handlers: the
+/// default `HashMapStorage` suits most uses, while `VecStorage` trades a
+/// touch of registration-time bookkeeping for cache-friendlier fan-out when
+/// many notification types are registered.
+pub struct Mediator<S: HandlerStorage + Send + 'static = HashMapStorage> {
     /// store handlers by request type ID
-    request_handlers: HashMap<TypeId, Box<dyn Any>>,
-    notification_handlers: HashMap<TypeId, Vec<Box<dyn Any>>>,
+    request_handlers: HashMap<TypeId, RequestSlot>,
+    /// store handlers and delivery mode by notification type ID, shared with
+    /// the queued-delivery worker thread
+    notification_handlers: notifications::HandlerMap<S>,
+    /// background worker draining `DeliveryMode::Queued` notifications
+    queue_worker: notifications::QueueWorker<S>,
+    /// store handlers registered for async dispatch, by request type ID
+    async_request_handlers: HashMap<TypeId, Box<dyn Any + Send + Sync>>,
+    /// worker pool backing `send_request_async`
+    pool: async_dispatch::WorkerPool,
 }
 
-impl Mediator {
-    /// Create a new empty mediator
+impl<S: HandlerStorage + Send + 'static> Mediator<S> {
+    /// Create a new empty mediator with a default-sized async worker pool
     pub fn new() -> Self {
+        Self::with_pool(DEFAULT_POOL_SIZE)
+    }
+
+    /// Create a new empty mediator whose async worker pool has `size` threads
+    pub fn with_pool(size: usize) -> Self {
+        let notification_handlers: notifications::HandlerMap<S> =
+            Arc::new(Mutex::new(S::default()));
+        let queue_worker = notifications::QueueWorker::spawn(Arc::clone(&notification_handlers));
+
         Self {
             request_handlers: HashMap::new(),
-            notification_handlers: HashMap::new(),
+            notification_handlers,
+            queue_worker,
+            async_request_handlers: HashMap::new(),
+            pool: async_dispatch::WorkerPool::new(size),
         }
     }
 
@@ -44,72 +93,191 @@ impl Mediator {
         // Box the request handler
         let boxed_handler: Box<dyn RequestHandler<R, Resp>> = Box::new(handler);
 
-        // Box it again as 'Any' so we can downcast it safely later
-        self.request_handlers.insert(type_id, Box::new(boxed_handler));
+        self.request_handlers.insert(
+            type_id,
+            RequestSlot {
+                response_type_name: type_name::<Resp>(),
+                // Box it again as 'Any' so we can downcast it safely later
+                handler: Box::new(boxed_handler),
+            },
+        );
     }
 
-    /// Register a handler for a given notification type
+    /// Register a handler for a given notification type, delivered `Immediate`ly
     pub fn register_notification<N, H>(&mut self, handler: H)
     where
-        N: Notification + 'static,
-        H: NotificationHandler<N> + 'static,
+        N: Notification + Clone + 'static,
+        H: NotificationHandler<N> + Send + Sync + 'static,
     {
-        let type_id = TypeId::of::<N>();
-
-        let entry = self
-            .notification_handlers
-            .entry(type_id)
-            .or_insert_with(Vec::new);
-
-        // Box the notification handler
-        let handler: Box<dyn NotificationHandler<N>> = Box::new(handler);
+        self.register_notification_with(handler, DeliveryMode::Immediate);
+    }
 
-        // Box it again as 'Any' so we can downcast it safely later
-        entry.push(Box::new(handler));
+    /// Register a handler for a given notification type with an explicit `DeliveryMode`
+    pub fn register_notification_with<N, H>(&mut self, handler: H, mode: DeliveryMode)
+    where
+        N: Notification + Clone + 'static,
+        H: NotificationHandler<N> + Send + Sync + 'static,
+    {
+        let type_id = TypeId::of::<N>();
+        let boxed_handler: Arc<dyn NotificationHandler<N> + Send + Sync> = Arc::new(handler);
+
+        let mut handlers = self.notification_handlers.lock().unwrap();
+        match handlers.get_mut(type_id) {
+            Some(entry) => {
+                entry.mode = mode;
+                entry.push::<N>(boxed_handler);
+                if mode == DeliveryMode::Coalesced && entry.coalesce.is_none() {
+                    entry.coalesce = Some(notifications::CoalesceSlot::new::<N>());
+                }
+            }
+            None => {
+                let mut entry = notifications::NotificationEntry::new(mode);
+                entry.push::<N>(boxed_handler);
+                if mode == DeliveryMode::Coalesced {
+                    entry.coalesce = Some(notifications::CoalesceSlot::new::<N>());
+                }
+                handlers.insert(type_id, entry);
+            }
+        }
     }
 
     /// Dispatch a request to the appropriate handler, and return the response
-    pub fn send_request<R, Resp>(&self, request: R) -> Result<Resp, String>
-    where 
+    pub fn send_request<R, Resp>(&self, request: R) -> Result<Resp, MediatorError>
+    where
         R: Request,
         Resp: 'static,
     {
         let type_id = TypeId::of::<R>();
 
-        // Get the boxed handler
-        let boxed_handler = self
+        // Get the registered slot
+        let slot = self
             .request_handlers
             .get(&type_id)
-            .ok_or_else(|| "No handler registered for request.".to_string())?;
+            .ok_or(MediatorError::NoHandler {
+                type_name: type_name::<R>(),
+            })?;
 
-        let handler = boxed_handler
+        let handler = slot
+            .handler
             .downcast_ref::<Box<dyn RequestHandler<R, Resp>>>()
-            .ok_or_else(|| "Handler found, but type mismatch occurred.".to_string())?;
+            .ok_or(MediatorError::TypeMismatch {
+                expected: type_name::<Resp>(),
+                found: slot.response_type_name,
+            })?;
+
+        error::Responder::respond(handler.as_ref(), request)
+    }
 
-        Ok(handler.handle(request))
+    /// Register a handler for a given request type that may be dispatched via
+    /// `send_request_async`
+    pub fn register_request_async<R, Resp, H>(&mut self, handler: H)
+    where
+        R: Request,
+        Resp: 'static,
+        H: CancellableRequestHandler<R, Resp>,
+    {
+        let type_id = TypeId::of::<R>();
+        self.async_request_handlers
+            .insert(type_id, async_dispatch::boxed_async_handler::<R, Resp, H>(handler));
     }
 
-    /// Publish a notification to all handlers registered for its type
+    /// Submit a request to the worker pool and return a handle to it immediately.
+    ///
+    /// Unlike `send_request`, the handler runs off the calling thread; the
+    /// returned `JobHandle` can be `.join()`ed for the response or `.cancel()`led
+    /// to ask a cooperating handler to stop early.
+    pub fn send_request_async<R, Resp>(&self, request: R) -> JobHandle<Resp>
+    where
+        R: Request + Send,
+        Resp: Send + 'static,
+    {
+        let type_id = TypeId::of::<R>();
+        let boxed_handler = self.async_request_handlers.get(&type_id);
+
+        async_dispatch::submit_async_request(&self.pool, boxed_handler, request)
+    }
+
+    /// Publish a notification to all handlers registered for its type.
+    ///
+    /// Dispatch follows the `DeliveryMode` the type was registered with:
+    /// `Immediate` handlers run inline before this call returns, `Queued`
+    /// handlers run later on the background worker (see `flush`).
     pub fn send_notification<N>(&self, notification: N)
-    where 
-        N: Notification + Clone,
+    where
+        N: Notification + Clone + Send,
     {
         let type_id = TypeId::of::<N>();
 
-        // Find handlers by the notification's TypeId
-        let handlers = match self.notification_handlers.get(&type_id) {
-            Some(h) => h,
-            None => return,
+        let mode = {
+            let handlers = self.notification_handlers.lock().unwrap();
+            match handlers.get(type_id) {
+                Some(entry) => entry.mode,
+                None => return,
+            }
         };
 
-        // Iterate over each of the stored boxed handlers
-        for boxed_handler in handlers {
-            // Attempt to downcast to the expected handler
-            if let Some(handler) = boxed_handler.downcast_ref::<Box<dyn NotificationHandler<N>>>()
-            {
-                // Clone the notification so that each handler has its own copy
-                handler.handle(notification.clone());
+        match mode {
+            DeliveryMode::Immediate => notifications::deliver::<N, S>(
+                &self.notification_handlers,
+                type_id,
+                notification,
+            ),
+            DeliveryMode::Queued => {
+                notifications::queue_notification::<N, S>(&self.queue_worker, type_id, notification)
             }
+            // A type registered `Coalesced` dispatches the same way `notify` does:
+            // store the payload for the next `drain_notifications` instead of
+            // delivering inline.
+            DeliveryMode::Coalesced => self.notify(notification),
+        }
+    }
+
+    /// Block until every `Queued` notification published so far has been
+    /// delivered to its handlers. Useful in tests and shutdown paths that
+    /// need queued delivery to behave deterministically.
+    pub fn flush(&self) {
+        self.queue_worker.flush();
+    }
+
+    /// Publish a `DeliveryMode::Coalesced` notification without delivering it.
+    ///
+    /// Repeated calls before the next `drain_notifications` collapse into a
+    /// single delivery of the latest payload; handlers never see one fired
+    /// for each call. A no-op if `N` wasn't registered with `Coalesced`.
+    pub fn notify<N>(&self, notification: N)
+    where
+        N: Notification + Send + 'static,
+    {
+        let type_id = TypeId::of::<N>();
+        let handlers = self.notification_handlers.lock().unwrap();
+        if let Some(coalesce) = handlers.get(type_id).and_then(|entry| entry.coalesce.as_ref()) {
+            coalesce.publish(notification);
+        }
+    }
+
+    /// Deliver the latest payload of every `Coalesced` notification type that
+    /// has a pending publish since the last drain, exactly once each.
+    pub fn drain_notifications(&self) {
+        // Collect every pending slot's delivery trampoline, payload, and
+        // handler snapshot while the registry is locked, then invoke them
+        // only after releasing the lock: a handler that calls back into the
+        // mediator (e.g. `notify` or `register_notification`) would
+        // otherwise deadlock on a lock it's still holding.
+        let deliveries = {
+            let handlers = self.notification_handlers.lock().unwrap();
+            let mut deliveries = Vec::new();
+            handlers.for_each(|entry| {
+                if let Some(coalesce) = &entry.coalesce {
+                    if let Some((deliver, payload)) = coalesce.take_pending() {
+                        deliveries.push((deliver, payload, entry.handlers.clone()));
+                    }
+                }
+            });
+            deliveries
+        };
+
+        for (deliver, payload, handlers) in deliveries {
+            deliver(payload.as_ref(), &handlers);
         }
     }
 }
@@ -139,13 +307,13 @@ mod tests {
 
     #[test]
     fn mediator_handles_registered_command() {
-        let mut mediator = Mediator::new();
+        let mut mediator = Mediator::<HashMapStorage>::new();
 
         // Register the Greet handler
         mediator.register_request::<Greet, String, _>(GreetHandler);
 
         // Send a Greet request
-        let result: Result<String, String> = mediator.send_request(Greet {
+        let result: Result<String, MediatorError> = mediator.send_request(Greet {
             name: "Alice".into()
         });
 
@@ -154,31 +322,64 @@ mod tests {
 
     #[test]
     fn mediator_returns_error_for_unregistered_command() {
-        let mediator = Mediator::new();
+        let mediator = Mediator::<HashMapStorage>::new();
 
         // Send a Greet request without registering the handler
         let result: Result<String, _> = mediator.send_request(Greet {
             name: "Bob".into()
         });
 
-        assert!(result.is_err());
-        assert_eq!(result.unwrap_err(), "No handler registered for request.");
+        assert!(matches!(
+            result.unwrap_err(),
+            MediatorError::NoHandler { .. }
+        ));
     }
 
     #[test]
     fn mediator_returns_error_for_handler_type_mismatch() {
-        let mut mediator = Mediator::new();
+        let mut mediator = Mediator::<HashMapStorage>::new();
 
         // Register the handler with the expected output (String)
         mediator.register_request::<Greet, String, _>(GreetHandler);
 
         // Send a Greet request with an invalid return type (usize)
-        let result: Result<usize, String> = mediator.send_request(Greet {
+        let result: Result<usize, MediatorError> = mediator.send_request(Greet {
             name: "Steve".into()
         });
 
-        assert!(result.is_err());
-        assert_eq!(result.unwrap_err(), "Handler found, but type mismatch occurred.");
+        match result.unwrap_err() {
+            MediatorError::TypeMismatch { expected, found } => {
+                assert_eq!(expected, std::any::type_name::<usize>());
+                assert_eq!(found, std::any::type_name::<String>());
+            }
+            other => panic!("expected TypeMismatch, got {other:?}"),
+        }
+    }
+
+    /// A handler whose `handle` always panics, to exercise `Responder`'s
+    /// `catch_unwind` through the public `send_request` path.
+    struct PanickingGreetHandler;
+
+    impl RequestHandler<Greet, String> for PanickingGreetHandler {
+        fn handle(&self, _request: Greet) -> String {
+            panic!("boom");
+        }
+    }
+
+    #[test]
+    fn mediator_returns_error_for_handler_panic() {
+        let mut mediator = Mediator::<HashMapStorage>::new();
+
+        mediator.register_request::<Greet, String, _>(PanickingGreetHandler);
+
+        let result: Result<String, MediatorError> = mediator.send_request(Greet {
+            name: "Carol".into(),
+        });
+
+        assert!(matches!(
+            result.unwrap_err(),
+            MediatorError::HandlerPanicked
+        ));
     }
 
     // Notification tests
@@ -214,7 +415,7 @@ mod tests {
         };
 
         // Create and register the handler with the mediator
-        let mut mediator = Mediator::new();
+        let mut mediator = Mediator::<HashMapStorage>::new();
         mediator.register_notification(handler);
 
         // Publish the notification
@@ -226,9 +427,155 @@ mod tests {
     #[test]
     fn mediator_ignores_unhandled_notification() {
         // Create the mediator but don't register any notifications
-        let mediator = Mediator::new();
+        let mediator = Mediator::<HashMapStorage>::new();
 
         // Publish a notification - it should not panic even when no handlers exist
         mediator.send_notification(Ping);
     }
+
+    #[test]
+    fn queued_notification_is_delivered_by_flush() {
+        let was_called = Arc::new(Mutex::new(false));
+        let handler = PingHandler {
+            was_called: Arc::clone(&was_called),
+        };
+
+        let mut mediator = Mediator::<HashMapStorage>::new();
+        mediator.register_notification_with(handler, DeliveryMode::Queued);
+
+        mediator.send_notification(Ping);
+        // Before flushing, delivery may not have happened yet.
+        mediator.flush();
+
+        assert_eq!(*was_called.lock().unwrap(), true);
+    }
+
+    #[test]
+    fn mediator_with_vec_storage_dispatches_notifications() {
+        let was_called = Arc::new(Mutex::new(false));
+        let handler = PingHandler {
+            was_called: Arc::clone(&was_called),
+        };
+
+        let mut mediator: Mediator<VecStorage> = Mediator::new();
+        mediator.register_notification(handler);
+        mediator.send_notification(Ping);
+
+        assert_eq!(*was_called.lock().unwrap(), true);
+    }
+
+    /// A test handler that counts how many times it's been called
+    struct CountingPingHandler {
+        call_count: Arc<Mutex<u32>>,
+    }
+
+    impl NotificationHandler<Ping> for CountingPingHandler {
+        fn handle(&self, _notification: Ping) {
+            *self.call_count.lock().unwrap() += 1;
+        }
+    }
+
+    #[test]
+    fn coalesced_notifications_collapse_into_a_single_drain() {
+        let call_count = Arc::new(Mutex::new(0));
+        let handler = CountingPingHandler {
+            call_count: Arc::clone(&call_count),
+        };
+
+        let mut mediator = Mediator::<HashMapStorage>::new();
+        mediator.register_notification_with(handler, DeliveryMode::Coalesced);
+
+        // A burst of publishes before a drain should deliver only once.
+        mediator.notify(Ping);
+        mediator.notify(Ping);
+        mediator.notify(Ping);
+        mediator.drain_notifications();
+
+        assert_eq!(*call_count.lock().unwrap(), 1);
+
+        // A second drain with nothing new pending delivers nothing further.
+        mediator.drain_notifications();
+        assert_eq!(*call_count.lock().unwrap(), 1);
+    }
+
+    /// A second, distinct notification type so a handler for one can publish
+    /// the other without it being the same `TypeId`.
+    #[derive(Clone)]
+    struct Pong;
+
+    impl Notification for Pong {}
+
+    /// A handler that publishes another notification from inside `handle`,
+    /// exercising the ordinary "handler fires another event" pattern. Holds
+    /// the shared handler map directly rather than the `Mediator` itself, so
+    /// it only needs `notifications::HandlerMap` to be `Send + Sync` (which
+    /// it already must be, to be shared with the queue worker thread).
+    struct ReentrantHandler {
+        handlers: notifications::HandlerMap<HashMapStorage>,
+        pong_type_id: TypeId,
+    }
+
+    impl NotificationHandler<Ping> for ReentrantHandler {
+        fn handle(&self, _notification: Ping) {
+            notifications::deliver::<Pong, HashMapStorage>(
+                &self.handlers,
+                self.pong_type_id,
+                Pong,
+            );
+        }
+    }
+
+    struct PongHandler {
+        was_called: Arc<Mutex<bool>>,
+    }
+
+    impl NotificationHandler<Pong> for PongHandler {
+        fn handle(&self, _notification: Pong) {
+            *self.was_called.lock().unwrap() = true;
+        }
+    }
+
+    #[test]
+    fn a_handler_may_trigger_another_notification_without_deadlocking() {
+        let pong_was_called = Arc::new(Mutex::new(false));
+
+        let mut mediator = Mediator::<HashMapStorage>::new();
+        mediator.register_notification(PongHandler {
+            was_called: Arc::clone(&pong_was_called),
+        });
+        mediator.register_notification(ReentrantHandler {
+            handlers: Arc::clone(&mediator.notification_handlers),
+            pong_type_id: TypeId::of::<Pong>(),
+        });
+
+        // Would hang forever if `send_notification` still held the
+        // notification registry lock while `ReentrantHandler::handle` ran.
+        mediator.send_notification(Ping);
+
+        assert_eq!(*pong_was_called.lock().unwrap(), true);
+    }
+
+    #[test]
+    fn a_drained_coalesced_handler_may_trigger_another_notification_without_deadlocking() {
+        let pong_was_called = Arc::new(Mutex::new(false));
+
+        let mut mediator = Mediator::<HashMapStorage>::new();
+        mediator.register_notification(PongHandler {
+            was_called: Arc::clone(&pong_was_called),
+        });
+        mediator.register_notification_with(
+            ReentrantHandler {
+                handlers: Arc::clone(&mediator.notification_handlers),
+                pong_type_id: TypeId::of::<Pong>(),
+            },
+            DeliveryMode::Coalesced,
+        );
+
+        mediator.notify(Ping);
+        // Would hang forever if `drain_notifications` still held the
+        // notification registry lock while invoking `ReentrantHandler::handle`.
+        mediator.drain_notifications();
+
+        assert_eq!(*pong_was_called.lock().unwrap(), true);
+    }
 }
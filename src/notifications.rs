@@ -0,0 +1,252 @@
+//! Notification delivery modes: synchronous in-line fan-out, or a bounded
+//! queue drained in batches by a background worker, for decoupling publishers
+//! from slow subscribers without forcing async onto the whole API.
+
+use std::any::{Any, TypeId};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crossbeam_channel::{bounded, Receiver, Sender};
+
+use crate::storage::HandlerStorage;
+use crate::{Notification, NotificationHandler};
+
+/// Capacity of the queued-delivery channel before `send_notification` blocks.
+const QUEUE_CAPACITY: usize = 1024;
+/// Upper bound on notifications drained from the queue per worker wakeup, so a
+/// publish burst can't starve the worker from ever catching up on latency.
+const MAX_BATCH_SIZE: usize = 4096;
+
+/// How a notification type's handlers are invoked when it's published.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryMode {
+    /// Handlers run inline, on the publisher's thread, before `send_notification` returns.
+    Immediate,
+    /// The notification is cloned onto a bounded queue and handlers run later,
+    /// in batches, on a background worker thread.
+    Queued,
+    /// Repeated publishes between drains collapse into a single delivery of
+    /// the latest payload, dispatched via `Mediator::notify`/`drain_notifications`.
+    Coalesced,
+}
+
+// An `Arc`, not a `Box`: `deliver` and `drain_notifications` need to clone a
+// snapshot of a type's handlers out from under the registry lock before
+// invoking them, so a handler that calls back into the mediator (e.g. to
+// publish another notification) doesn't deadlock on a lock it already holds.
+type BoxedHandler<N> = Arc<dyn NotificationHandler<N> + Send + Sync>;
+
+/// One registered notification type: its delivery mode and handler list.
+pub struct NotificationEntry {
+    pub(crate) mode: DeliveryMode,
+    pub(crate) handlers: Vec<Arc<dyn Any + Send + Sync>>,
+    pub(crate) coalesce: Option<CoalesceSlot>,
+}
+
+impl NotificationEntry {
+    pub(crate) fn new(mode: DeliveryMode) -> Self {
+        Self {
+            mode,
+            handlers: Vec::new(),
+            coalesce: None,
+        }
+    }
+
+    pub(crate) fn push<N: Notification>(&mut self, handler: BoxedHandler<N>) {
+        self.handlers.push(Arc::new(handler));
+    }
+}
+
+type DeliverFn = Arc<dyn Fn(&(dyn Any + Send), &[Arc<dyn Any + Send + Sync>]) + Send + Sync>;
+
+/// Coalescing state for a `DeliveryMode::Coalesced` notification type: the
+/// latest payload `notify` writes and `drain_notifications` reads, plus a
+/// type-erased trampoline back into the concrete `N` so `drain_notifications`
+/// can fan a payload out without knowing `N` itself.
+///
+/// `publish` and `take_pending` are only ever called while the caller holds
+/// the `Mediator`'s single `notification_handlers` lock, which already
+/// serializes every access to a slot. The `Mutex` here exists purely to give
+/// `&self` interior mutability, not to arbitrate a race between concurrent
+/// publishers and drainers — there isn't one under the current locking.
+pub(crate) struct CoalesceSlot {
+    payload: Mutex<Option<Box<dyn Any + Send>>>,
+    deliver: DeliverFn,
+}
+
+impl CoalesceSlot {
+    pub(crate) fn new<N>() -> Self
+    where
+        N: Notification + Clone + 'static,
+    {
+        Self {
+            payload: Mutex::new(None),
+            deliver: Arc::new(|payload, handlers| {
+                let Some(notification) = payload.downcast_ref::<N>() else {
+                    return;
+                };
+                for boxed_handler in handlers {
+                    if let Some(handler) = boxed_handler.downcast_ref::<BoxedHandler<N>>() {
+                        handler.handle(notification.clone());
+                    }
+                }
+            }),
+        }
+    }
+
+    /// Store the latest payload. A publish that arrives before the next
+    /// drain overwrites it instead of queuing a second delivery.
+    pub(crate) fn publish<N>(&self, notification: N)
+    where
+        N: Notification + Send + 'static,
+    {
+        *self.payload.lock().unwrap() = Some(Box::new(notification));
+    }
+
+    /// If a payload is pending, take it and return it along with this slot's
+    /// type-erased delivery trampoline.
+    ///
+    /// Delivery itself is left to the caller, deliberately: invoking a
+    /// handler here would mean calling it while the caller still holds the
+    /// registry lock it read this slot through, and a handler that calls
+    /// back into the mediator would deadlock on it.
+    pub(crate) fn take_pending(&self) -> Option<(DeliverFn, Box<dyn Any + Send>)> {
+        let payload = self.payload.lock().unwrap().take()?;
+        Some((Arc::clone(&self.deliver), payload))
+    }
+}
+
+/// Shared, lockable handler storage, generic over the backing `HandlerStorage` impl.
+pub(crate) type HandlerMap<S> = Arc<Mutex<S>>;
+type QueueJob<S> = Box<dyn FnOnce(&HandlerMap<S>) + Send>;
+
+/// Background worker draining the queued-delivery channel.
+pub(crate) struct QueueWorker<S> {
+    sender: Sender<QueueJob<S>>,
+}
+
+impl<S: HandlerStorage + Send + 'static> QueueWorker<S> {
+    pub(crate) fn spawn(handlers: HandlerMap<S>) -> Self {
+        let (sender, receiver): (Sender<QueueJob<S>>, Receiver<QueueJob<S>>) =
+            bounded(QUEUE_CAPACITY);
+
+        thread::spawn(move || {
+            while let Ok(first) = receiver.recv() {
+                first(&handlers);
+                // The worker wakes for every queued notification; there is no
+                // minimum queue depth before it runs. Whatever else is already
+                // sitting in the channel is drained in the same batch, capped so
+                // a runaway publisher still bounds a single wakeup's latency.
+                for job in receiver.try_iter().take(MAX_BATCH_SIZE - 1) {
+                    job(&handlers);
+                }
+            }
+        });
+
+        Self { sender }
+    }
+
+    fn submit(&self, job: QueueJob<S>) {
+        // Queued delivery is best-effort past a worker shutdown; a send
+        // failure here means the worker thread is gone, so there's nothing
+        // left to deliver to.
+        let _ = self.sender.send(job);
+    }
+
+    /// Block until every notification submitted so far has been delivered.
+    pub(crate) fn flush(&self) {
+        let (done_tx, done_rx) = bounded::<()>(0);
+        self.submit(Box::new(move |_| {
+            let _ = done_tx.send(());
+        }));
+        let _ = done_rx.recv();
+    }
+}
+
+pub(crate) fn queue_notification<N, S>(worker: &QueueWorker<S>, type_id: TypeId, notification: N)
+where
+    N: Notification + Clone + Send + 'static,
+    S: HandlerStorage + Send + 'static,
+{
+    worker.submit(Box::new(move |handlers: &HandlerMap<S>| {
+        deliver::<N, S>(handlers, type_id, notification);
+    }));
+}
+
+pub(crate) fn deliver<N, S>(handlers: &HandlerMap<S>, type_id: TypeId, notification: N)
+where
+    N: Notification + Clone + 'static,
+    S: HandlerStorage,
+{
+    // Snapshot the handler `Arc`s and release the lock before invoking any of
+    // them: a handler that calls back into the mediator (e.g. to send another
+    // notification) would otherwise deadlock on a lock it's still holding.
+    let snapshot: Vec<Arc<dyn Any + Send + Sync>> = {
+        let handlers = handlers.lock().unwrap();
+        match handlers.get(type_id) {
+            Some(entry) => entry.handlers.clone(),
+            None => return,
+        }
+    };
+    for boxed_handler in &snapshot {
+        if let Some(handler) = boxed_handler.downcast_ref::<BoxedHandler<N>>() {
+            handler.handle(notification.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::HashMapStorage;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[derive(Clone)]
+    struct Counted;
+
+    impl Notification for Counted {}
+
+    struct CountingHandler {
+        count: Arc<AtomicUsize>,
+    }
+
+    impl NotificationHandler<Counted> for CountingHandler {
+        fn handle(&self, _notification: Counted) {
+            self.count.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn queued_notifications_are_delivered_after_flush() {
+        let handlers: HandlerMap<HashMapStorage> = Arc::new(Mutex::new(HashMapStorage::default()));
+        let count = Arc::new(AtomicUsize::new(0));
+        let type_id = TypeId::of::<Counted>();
+
+        {
+            let mut handlers = handlers.lock().unwrap();
+            let mut entry = NotificationEntry::new(DeliveryMode::Queued);
+            entry.push::<Counted>(Arc::new(CountingHandler {
+                count: Arc::clone(&count),
+            }));
+            handlers.insert(type_id, entry);
+        }
+
+        let worker = QueueWorker::spawn(Arc::clone(&handlers));
+
+        for _ in 0..10 {
+            queue_notification(&worker, type_id, Counted);
+        }
+        worker.flush();
+
+        assert_eq!(count.load(Ordering::SeqCst), 10);
+    }
+
+    #[test]
+    fn flush_on_an_idle_worker_returns_immediately() {
+        let handlers: HandlerMap<HashMapStorage> = Arc::new(Mutex::new(HashMapStorage::default()));
+        let worker = QueueWorker::spawn(handlers);
+        worker.flush();
+        thread::sleep(Duration::from_millis(1));
+    }
+}
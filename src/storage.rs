@@ -0,0 +1,115 @@
+//! Pluggable backing stores for a `Mediator`'s registered notification
+//! handlers. `HashMapStorage` is the default; `VecStorage` assigns each
+//! notification type a dense `EventId` so walking every registered type
+//! (`for_each`) runs over a contiguous `Vec` without hashing any `TypeId`.
+
+use std::any::TypeId;
+use std::collections::HashMap;
+
+use crate::notifications::NotificationEntry;
+
+/// Backing store for a `Mediator`'s registered notification handlers.
+pub trait HandlerStorage: Default {
+    /// Look up the slot registered for `id`, if any.
+    fn get(&self, id: TypeId) -> Option<&NotificationEntry>;
+    /// Look up the slot registered for `id` for in-place mutation.
+    fn get_mut(&mut self, id: TypeId) -> Option<&mut NotificationEntry>;
+    /// Visit every registered slot, regardless of its `TypeId`.
+    fn for_each<F: FnMut(&NotificationEntry)>(&self, f: F);
+    /// Register a new slot under `id`. Callers are expected to check
+    /// `get_mut` first; calling this for an `id` that's already present
+    /// is a logic error left to the implementation to define.
+    fn insert(&mut self, id: TypeId, slot: NotificationEntry);
+}
+
+/// Default notification storage: a plain `TypeId`-keyed hash map.
+#[derive(Default)]
+pub struct HashMapStorage {
+    slots: HashMap<TypeId, NotificationEntry>,
+}
+
+impl HandlerStorage for HashMapStorage {
+    fn get(&self, id: TypeId) -> Option<&NotificationEntry> {
+        self.slots.get(&id)
+    }
+
+    fn get_mut(&mut self, id: TypeId) -> Option<&mut NotificationEntry> {
+        self.slots.get_mut(&id)
+    }
+
+    fn for_each<F: FnMut(&NotificationEntry)>(&self, mut f: F) {
+        for slot in self.slots.values() {
+            f(slot);
+        }
+    }
+
+    fn insert(&mut self, id: TypeId, slot: NotificationEntry) {
+        self.slots.insert(id, slot);
+    }
+}
+
+/// Dense index into a `VecStorage`'s slot list, assigned the first time its
+/// notification type is registered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct EventId(usize);
+
+/// Notification storage that assigns each registered type a dense `EventId`
+/// and keeps slots in a contiguous `Vec`. `for_each` walks that `Vec`
+/// directly; `get` and `get_mut` still hash the `TypeId` to find its `EventId`.
+#[derive(Default)]
+pub struct VecStorage {
+    index: HashMap<TypeId, EventId>,
+    slots: Vec<NotificationEntry>,
+}
+
+impl HandlerStorage for VecStorage {
+    fn get(&self, id: TypeId) -> Option<&NotificationEntry> {
+        let event_id = *self.index.get(&id)?;
+        Some(&self.slots[event_id.0])
+    }
+
+    fn get_mut(&mut self, id: TypeId) -> Option<&mut NotificationEntry> {
+        let event_id = *self.index.get(&id)?;
+        Some(&mut self.slots[event_id.0])
+    }
+
+    fn for_each<F: FnMut(&NotificationEntry)>(&self, mut f: F) {
+        for slot in &self.slots {
+            f(slot);
+        }
+    }
+
+    fn insert(&mut self, id: TypeId, slot: NotificationEntry) {
+        let event_id = EventId(self.slots.len());
+        self.slots.push(slot);
+        self.index.insert(id, event_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notifications::DeliveryMode;
+
+    fn slot() -> NotificationEntry {
+        NotificationEntry::new(DeliveryMode::Immediate)
+    }
+
+    #[test]
+    fn vec_storage_assigns_dense_ids_and_round_trips_slots() {
+        let mut storage = VecStorage::default();
+        let a = TypeId::of::<u8>();
+        let b = TypeId::of::<u16>();
+
+        storage.insert(a, slot());
+        storage.insert(b, slot());
+
+        assert!(storage.get(a).is_some());
+        assert!(storage.get(b).is_some());
+        assert!(storage.get(TypeId::of::<u32>()).is_none());
+
+        let mut seen = 0;
+        storage.for_each(|_| seen += 1);
+        assert_eq!(seen, 2);
+    }
+}